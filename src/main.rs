@@ -1,10 +1,108 @@
-use clap::Parser;
-use evdev::{Device, EventType, KeyCode};
+use clap::{Parser, ValueEnum};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{AttributeSet, Device, EventType, KeyCode};
 use inotify::{EventMask, Inotify, WatchMask};
-use std::collections::HashMap;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::io::ErrorKind;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::Path;
 use std::str::FromStr;
 
+/// Whether a binding unmutes only while held, or flips the mute state on each full press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum BindingMode {
+    #[default]
+    Hold,
+    Toggle,
+}
+
+/// Whether a binding's `keys` form a chord (all must be held at once) or a set of
+/// alternatives (any one of them activates it).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum BindingMatch {
+    #[default]
+    All,
+    Any,
+}
+
+#[derive(Debug, Deserialize)]
+struct BindingConfig {
+    keys: Vec<String>,
+    #[serde(default)]
+    mode: BindingMode,
+    #[serde(rename = "match", default)]
+    match_mode: BindingMatch,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandBackendConfig {
+    mute: String,
+    unmute: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    bindings: Vec<BindingConfig>,
+    command: Option<CommandBackendConfig>,
+}
+
+impl Config {
+    fn load(path: &Path) -> Result<Config, String> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| format!("could not read file: {}", e))?;
+        toml::from_str(&contents).map_err(|e| format!("invalid config: {}", e))
+    }
+}
+
+/// A trigger made of one or more keys: `match_mode` decides whether `keys` is a chord (all must
+/// be held at once) or a set of alternatives (any one of them activates it), and `mode` decides
+/// whether being active unmutes or each full activation toggles mute.
+#[derive(Debug)]
+struct Binding {
+    keys: Vec<KeyCode>,
+    mode: BindingMode,
+    match_mode: BindingMatch,
+    // Whether this binding was active the last time it was evaluated, so mode handling only
+    // reacts on the activate/deactivate edge rather than on every matching key event.
+    active: bool,
+}
+
+fn parse_binding(config: &BindingConfig) -> Result<Binding, String> {
+    if config.keys.is_empty() {
+        // An empty chord is vacuously satisfied under `match = "all"` (every element of an
+        // empty set matches), which would make the binding active from startup.
+        return Err("binding has no keys".to_string());
+    }
+    let mut keys = Vec::with_capacity(config.keys.len());
+    for name in &config.keys {
+        let key = KeyCode::from_str(name).map_err(|e| format!("invalid key {}: {:?}", name, e))?;
+        keys.push(key);
+    }
+    Ok(Binding {
+        keys,
+        mode: config.mode,
+        match_mode: config.match_mode,
+        active: false,
+    })
+}
+
+fn parse_bindings(configs: &[BindingConfig]) -> Result<Vec<Binding>, String> {
+    configs.iter().map(parse_binding).collect()
+}
+
+/// Which `MuteBackend` to mute/unmute through, selectable via `--backend`.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum BackendKind {
+    Pactl,
+    Wpctl,
+    Command,
+}
+
 fn get_devices() -> Vec<String> {
     let paths = std::fs::read_dir("/dev/input").unwrap();
     let mut vec = std::vec::Vec::new();
@@ -22,133 +120,406 @@ fn get_devices() -> Vec<String> {
     return vec;
 }
 
-struct PushToTalk {
-    device: Device,
-    push_to_talk_key: KeyCode,
+fn list_devices() {
+    for name in get_devices() {
+        let path = format!("/dev/input/{}", name);
+        match Device::open(&path) {
+            Ok(d) => println!("{}: {}", path, d.name().unwrap_or("<unknown>")),
+            Err(e) => println!("Could not open {}. Error: {}", path, e),
+        }
+    }
 }
 
-impl PushToTalk {
-    fn new(device: Device, key: KeyCode) -> Self {
-        println!("Adding new listener for {}", device.name().unwrap());
-        Self {
-            device,
-            push_to_talk_key: key,
+// Puts `fd` in non-blocking mode so epoll-driven reads never stall the single event loop.
+fn set_nonblocking(fd: RawFd) -> nix::Result<()> {
+    let flags = OFlag::from_bits_truncate(fcntl(fd, FcntlArg::F_GETFL)?);
+    fcntl(fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+    Ok(())
+}
+
+// Builds the shared virtual output device that grabbed input is re-emitted through, covering the
+// full evdev key range so it can stand in for any physical keyboard.
+fn build_output_device() -> std::io::Result<VirtualDevice> {
+    // KEY_MAX from linux/input-event-codes.h; covers every key a physical keyboard can report.
+    let mut keys = AttributeSet::<KeyCode>::new();
+    for code in 0..=0x2ffu16 {
+        keys.insert(KeyCode::new(code));
+    }
+    VirtualDeviceBuilder::new()?
+        .name("pushtotalk-passthrough")
+        .with_keys(&keys)?
+        .build()
+}
+
+/// How a `PushToTalk` actually mutes and unmutes the microphone.
+trait MuteBackend {
+    fn set_mute(&mut self, mute: bool);
+}
+
+/// Shells out to `pactl`. `@DEFAULT_SOURCE@` is a live pactl token, not a name to resolve once:
+/// passing it straight through means mute always targets whatever the current default source is,
+/// even if the user switches it while the program is running.
+struct PactlBackend;
+
+impl MuteBackend for PactlBackend {
+    fn set_mute(&mut self, mute: bool) {
+        if let Err(e) = std::process::Command::new("pactl")
+            .args(["set-source-mute", "@DEFAULT_SOURCE@", &mute.to_string()])
+            .output()
+        {
+            println!("Failed to run pactl: {}", e);
         }
     }
+}
 
-    fn listen(&mut self) {
-        let dev = &mut self.device;
-        loop {
-            let event_result = dev.fetch_events();
-            match event_result {
-                Ok(events) => {
-                    for event in events {
-                        if event.event_type() == EventType::KEY {
-                            //println!("Got event val {} {}", event.value(), event.code());
-                            let pressed_key = KeyCode::new(event.code());
-                            PushToTalk::handle_key(
-                                &self.push_to_talk_key,
-                                &pressed_key,
-                                event.value(),
-                            );
-                        }
-                    }
-                }
+/// Native PipeWire backend, for setups without pipewire-pulse where `pactl` isn't available.
+struct WpctlBackend;
 
-                Err(event) => {
-                    println!("Failed to fetch events {}", event);
-                    break;
-                }
-            }
+impl MuteBackend for WpctlBackend {
+    fn set_mute(&mut self, mute: bool) {
+        let value = if mute { "1" } else { "0" };
+        if let Err(e) = std::process::Command::new("wpctl")
+            .args(["set-mute", "@DEFAULT_AUDIO_SOURCE@", value])
+            .output()
+        {
+            println!("Failed to run wpctl: {}", e);
         }
     }
+}
+
+/// Runs a user-supplied shell command template from the config file instead of a fixed tool.
+struct CommandBackend {
+    mute_command: String,
+    unmute_command: String,
+}
+
+impl MuteBackend for CommandBackend {
+    fn set_mute(&mut self, mute: bool) {
+        let command = if mute {
+            &self.mute_command
+        } else {
+            &self.unmute_command
+        };
+        if let Err(e) = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+        {
+            println!("Failed to run mute command '{}': {}", command, e);
+        }
+    }
+}
+
+// A single physical input device. Key events from it are fed into the manager's shared binding
+// state, since chords and mute state are not meaningful per-device (see PushToTalkManager).
+struct PushToTalk {
+    name: String,
+    device: Device,
+    grab: bool,
+}
 
-    fn handle_key(ptt_key: &KeyCode, key: &KeyCode, value: i32) {
-        //println!("Handling key {}, ppt key {}", key.0, PUSH_TO_TALK_KEY.0);
-        if *key == *ptt_key {
-            if value == 1 {
-                PushToTalk::set_mute(false);
-            } else if value == 0 {
-                PushToTalk::set_mute(true);
+impl PushToTalk {
+    fn new(name: String, mut device: Device, grab: bool) -> Self {
+        println!("Adding new listener for {}", device.name().unwrap());
+        if grab {
+            if let Err(e) = device.grab() {
+                println!("Failed to grab {}: {}", name, e);
             }
         }
+        Self { name, device, grab }
     }
+}
 
-    fn set_mute(mute: bool) {
-        std::process::Command::new("pactl")
-            .args(["set-source-mute", "@DEFAULT_SOURCE@", &mute.to_string()])
-            .output()
-            .expect("Failed to run pactl");
+fn build_backend(
+    kind: BackendKind,
+    command_backend: Option<CommandBackendConfig>,
+) -> Box<dyn MuteBackend> {
+    match kind {
+        BackendKind::Pactl => Box::new(PactlBackend),
+        BackendKind::Wpctl => Box::new(WpctlBackend),
+        BackendKind::Command => {
+            let config = command_backend
+                .expect("--backend command requires a [command] section in --config");
+            Box::new(CommandBackend {
+                mute_command: config.mute,
+                unmute_command: config.unmute,
+            })
+        }
     }
 }
 
 struct PushToTalkManager {
-    listener: HashMap<String, std::thread::JoinHandle<PushToTalk>>,
-    key: KeyCode,
+    devices: HashMap<RawFd, PushToTalk>,
+    epoll: Epoll,
+    inotify: Inotify,
+    // Bindings, pressed keys and mute state are shared across every listened device so a chord
+    // can be satisfied by keys on different keyboards, and so two keyboards can't independently
+    // (and inconsistently) mute/unmute.
+    bindings: Vec<Binding>,
+    pressed: HashSet<KeyCode>,
+    muted: bool,
+    // Resting mute state controlled by Toggle bindings, independent of whatever a Hold binding is
+    // currently forcing `muted` to. Hold always wins while active; this is what it falls back to.
+    toggle_muted: bool,
+    backend: Box<dyn MuteBackend>,
+    device_name: Option<String>,
+    device_paths: Vec<String>,
+    grab: bool,
+    output: Option<VirtualDevice>,
 }
 
 impl PushToTalkManager {
-    fn new(key: KeyCode) -> Self {
+    fn new(
+        bindings: Vec<Binding>,
+        device_name: Option<String>,
+        device_paths: Vec<String>,
+        grab: bool,
+        backend_kind: BackendKind,
+        command_backend: Option<CommandBackendConfig>,
+    ) -> Self {
+        let epoll = Epoll::new(EpollCreateFlags::empty()).expect("Failed to create epoll instance");
+
+        let output = if grab {
+            Some(build_output_device().expect("Failed to create uinput output device"))
+        } else {
+            None
+        };
+
+        let mut inotify = Inotify::init().expect("Error while initializing inotify instance");
+        inotify
+            .add_watch("/dev/input", WatchMask::DELETE | WatchMask::ATTRIB)
+            .expect("Failed to add file watch");
+        set_nonblocking(inotify.as_raw_fd()).expect("Failed to make inotify non-blocking");
+        epoll
+            .add(
+                &inotify,
+                EpollEvent::new(EpollFlags::EPOLLIN, inotify.as_raw_fd() as u64),
+            )
+            .expect("Failed to register inotify with epoll");
+
         Self {
-            listener: HashMap::new(),
-            key,
+            devices: HashMap::new(),
+            epoll,
+            inotify,
+            bindings,
+            pressed: HashSet::new(),
+            muted: true,
+            toggle_muted: true,
+            backend: build_backend(backend_kind, command_backend),
+            device_name,
+            device_paths,
+            grab,
+            output,
         }
     }
+
+    fn is_bound(&self, key: KeyCode) -> bool {
+        self.bindings
+            .iter()
+            .any(|binding| binding.keys.contains(&key))
+    }
+
+    fn handle_key(&mut self, key: KeyCode, value: i32) {
+        match value {
+            1 => {
+                self.pressed.insert(key);
+            }
+            0 => {
+                self.pressed.remove(&key);
+            }
+            // Ignore autorepeat (value 2); chord state only changes on press/release.
+            _ => return,
+        }
+
+        for binding in &mut self.bindings {
+            let now_active = match binding.match_mode {
+                BindingMatch::All => binding.keys.iter().all(|k| self.pressed.contains(k)),
+                BindingMatch::Any => binding.keys.iter().any(|k| self.pressed.contains(k)),
+            };
+            if now_active == binding.active {
+                continue;
+            }
+            binding.active = now_active;
+            if binding.mode == BindingMode::Toggle && now_active {
+                self.toggle_muted = !self.toggle_muted;
+            }
+        }
+
+        // Holding any Hold binding always unmutes, regardless of how many others are also held;
+        // once none of them are held, mute state falls back to whatever toggling last left it at.
+        let any_hold_active = self
+            .bindings
+            .iter()
+            .any(|b| b.mode == BindingMode::Hold && b.active);
+        let target_muted = if any_hold_active {
+            false
+        } else {
+            self.toggle_muted
+        };
+        if target_muted != self.muted {
+            self.muted = target_muted;
+            self.backend.set_mute(self.muted);
+        }
+    }
+
+    // Decide whether a freshly opened device should get a listener. With no
+    // filter configured every device is accepted, same as before this was
+    // added (just without the "keyboard" substring guess).
+    fn device_matches(&self, path: &str, device: &Device) -> bool {
+        self.matches_filters(path, device.name())
+    }
+
+    // Pulled out of `device_matches` so the path/name filtering logic can be tested without
+    // needing a real evdev `Device` to read a name from.
+    fn matches_filters(&self, path: &str, name: Option<&str>) -> bool {
+        if !self.device_paths.is_empty() {
+            return self.device_paths.iter().any(|p| p == path);
+        }
+        if let Some(wanted) = &self.device_name {
+            return name == Some(wanted.as_str());
+        }
+        true
+    }
+
     fn on_new_device(&mut self, name: String) {
-        let dev = Device::open(format!("/dev/input/{}", name));
+        let path = format!("/dev/input/{}", name);
+        let dev = Device::open(&path);
         match dev {
             Ok(d) => {
-                if !(d
-                    .name()
-                    .unwrap_or_default()
-                    .to_lowercase()
-                    .contains("keyboard"))
+                if !self.device_matches(&path, &d) {
+                    return;
+                }
+                if let Err(e) = set_nonblocking(d.as_raw_fd()) {
+                    println!("Could not make {} non-blocking. Error: {}", name, e);
+                    return;
+                }
+                let fd = d.as_raw_fd();
+                let ptt = PushToTalk::new(name, d, self.grab);
+                if let Err(e) = self
+                    .epoll
+                    .add(&ptt.device, EpollEvent::new(EpollFlags::EPOLLIN, fd as u64))
                 {
+                    println!("Failed to register {} with epoll: {}", ptt.name, e);
                     return;
                 }
-                let mut ptt = PushToTalk::new(d, self.key);
-
-                let thread = std::thread::spawn(move || {
-                    ptt.listen();
-                    ptt
-                });
-                self.listener.insert(name, thread);
+                self.devices.insert(fd, ptt);
             }
             Err(e) => println!("Could not open {}. Error: {}", name, e),
         }
     }
 
-    fn on_delete_device(&mut self, name: String) {
-        if self.listener.contains_key(&name) {
-            self.listener.remove(&name);
+    fn on_delete_device(&mut self, fd: RawFd) {
+        if let Some(ptt) = self.devices.remove(&fd) {
+            let _ = self.epoll.delete(&ptt.device);
+            println!("Removed listener for {}", ptt.name);
         }
     }
 
-    fn watch_inputs(&mut self) {
-        // Setup inotify listener
-        let mut inotify = Inotify::init().expect("Error while initializing inotify instance");
-        inotify
-            .add_watch("/dev/input", WatchMask::DELETE | WatchMask::ATTRIB)
-            .expect("Failed to add file watch");
+    fn on_delete_device_by_name(&mut self, name: &str) {
+        let fd = self
+            .devices
+            .iter()
+            .find(|(_, ptt)| ptt.name == name)
+            .map(|(fd, _)| *fd);
+        if let Some(fd) = fd {
+            self.on_delete_device(fd);
+        }
+    }
+
+    fn handle_device_event(&mut self, fd: RawFd) {
+        let Some(ptt) = self.devices.get_mut(&fd) else {
+            return;
+        };
+        let name = ptt.name.clone();
+        let grab = ptt.grab;
+        // Collecting into an owned Vec drops the borrow of `ptt`/`self.devices` before we need
+        // to touch the manager's shared binding state below.
+        let fetch_result = ptt
+            .device
+            .fetch_events()
+            .map(|events| events.collect::<Vec<_>>());
+        match fetch_result {
+            Ok(events) => {
+                let mut forward = Vec::new();
+                for event in events {
+                    if event.event_type() == EventType::KEY {
+                        let key = KeyCode::new(event.code());
+                        if self.is_bound(key) {
+                            self.handle_key(key, event.value());
+                            if grab {
+                                // Bound keys are consumed, not forwarded.
+                                continue;
+                            }
+                        }
+                    }
+                    // The output device only advertises EV_KEY (and the EV_SYN every uinput
+                    // device gets for free), so other event types physical keyboards send
+                    // (e.g. EV_MSC/MSC_SCAN) would make emit() fail and break forwarding
+                    // entirely if we tried to pass them through.
+                    if grab
+                        && event.event_type() != EventType::KEY
+                        && event.event_type() != EventType::SYNCHRONIZATION
+                    {
+                        continue;
+                    }
+                    forward.push(event);
+                }
+                if grab {
+                    if let Some(output) = &mut self.output {
+                        if let Err(e) = output.emit(&forward) {
+                            println!("Failed to forward events from {}: {}", name, e);
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) if e.raw_os_error() == Some(nix::libc::ENODEV) => {
+                println!("{} was unplugged", name);
+                self.on_delete_device(fd);
+            }
+            Err(e) => {
+                println!("Failed to fetch events: {}", e);
+                self.on_delete_device(fd);
+            }
+        }
+    }
 
+    fn handle_inotify_event(&mut self) {
         let mut buffer = [0; 1024];
         loop {
-            let events = loop {
-                match inotify.read_events_blocking(&mut buffer) {
-                    Ok(events) => break events,
-                    Err(error) if error.kind() == ErrorKind::WouldBlock => continue,
-                    _ => panic!("Error while reading events"),
-                }
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => panic!("Error while reading inotify events: {}", e),
             };
 
             for event in events {
                 let name = event.name.unwrap().to_str().unwrap().to_string();
                 // XXX: CREATE is too fast. We need to wait for ATTRIB. If this bind already exists, it doesn't matter
-                if event.mask == inotify::EventMask::ATTRIB {
+                if event.mask == EventMask::ATTRIB {
                     println!("Attr changed on {}", name);
                     self.on_new_device(name);
                 } else if event.mask == EventMask::DELETE {
-                    self.on_delete_device(name);
+                    self.on_delete_device_by_name(&name);
+                }
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        let inotify_fd = self.inotify.as_raw_fd();
+        let mut events = vec![EpollEvent::empty(); 16];
+        loop {
+            let n = self
+                .epoll
+                .wait(&mut events, EpollTimeout::NONE)
+                .expect("epoll_wait failed");
+            for event in &events[..n] {
+                let fd = event.data() as RawFd;
+                if fd == inotify_fd {
+                    self.handle_inotify_event();
+                } else {
+                    self.handle_device_event(fd);
                 }
             }
         }
@@ -161,21 +532,294 @@ struct Cli {
     /// Specify the key to use. Most keys have the form KEY_<NAME>
     #[arg(short, long, default_value = "KEY_CAPSLOCK")]
     key: String,
+
+    /// List all input devices with their path and name, then exit
+    #[arg(long)]
+    list_devices: bool,
+
+    /// Only listen on the device whose reported name matches exactly. Ignored when
+    /// --device-path is given
+    #[arg(long)]
+    device: Option<String>,
+
+    /// Only listen on this device path (e.g. /dev/input/event3). May be given multiple times
+    #[arg(long)]
+    device_path: Vec<String>,
+
+    /// Grab listened devices exclusively and re-emit their key and sync events, minus every bound
+    /// key, through a virtual uinput device, so bound keys never reach other applications. Events
+    /// of other types (e.g. EV_MSC, EV_REL) from grabbed devices are not re-emitted
+    #[arg(long)]
+    grab: bool,
+
+    /// Load key bindings (multiple keys, chords, hold/toggle modes) from a TOML config file,
+    /// instead of the single hold-to-talk `--key`
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+
+    /// Which tool to mute/unmute through. `command` runs the `[command]` section of `--config`
+    #[arg(long, value_enum, default_value = "pactl")]
+    backend: BackendKind,
 }
 
 fn main() {
     let cli = Cli::parse();
-    let key_res = KeyCode::from_str(&cli.key);
-    match key_res {
-        Ok(key) => {
-            let mut manager = PushToTalkManager::new(key);
-            println!("Starting global PTT with key {:?}", key);
-            let device_names = get_devices();
-            for device_name in device_names {
-                manager.on_new_device(device_name);
+
+    if cli.list_devices {
+        list_devices();
+        return;
+    }
+
+    let (bindings, command_backend) = if let Some(path) = &cli.config {
+        match Config::load(path) {
+            Ok(config) => match parse_bindings(&config.bindings) {
+                Ok(bindings) => (bindings, config.command),
+                Err(e) => {
+                    println!("Failed to load config {}: {}", path.display(), e);
+                    return;
+                }
+            },
+            Err(e) => {
+                println!("Failed to load config {}: {}", path.display(), e);
+                return;
             }
-            manager.watch_inputs();
         }
-        Err(e) => println!("Prodived an invalid key: {:?}", e),
+    } else {
+        match KeyCode::from_str(&cli.key) {
+            Ok(key) => (
+                vec![Binding {
+                    keys: vec![key],
+                    mode: BindingMode::Hold,
+                    match_mode: BindingMatch::All,
+                    active: false,
+                }],
+                None,
+            ),
+            Err(e) => {
+                println!("Prodived an invalid key: {:?}", e);
+                return;
+            }
+        }
+    };
+
+    if matches!(cli.backend, BackendKind::Command) && command_backend.is_none() {
+        println!("--backend command requires a [command] section in --config");
+        return;
+    }
+
+    println!("Starting global PTT with bindings {:?}", bindings);
+    let mut manager = PushToTalkManager::new(
+        bindings,
+        cli.device,
+        cli.device_path,
+        cli.grab,
+        cli.backend,
+        command_backend,
+    );
+    let device_names = get_devices();
+    for device_name in device_names {
+        manager.on_new_device(device_name);
+    }
+    manager.run();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager_with_filters(device_name: Option<&str>, device_paths: &[&str]) -> PushToTalkManager {
+        PushToTalkManager::new(
+            Vec::new(),
+            device_name.map(str::to_string),
+            device_paths.iter().map(|p| p.to_string()).collect(),
+            false,
+            BackendKind::Pactl,
+            None,
+        )
+    }
+
+    #[test]
+    fn matches_filters_accepts_everything_with_no_filter() {
+        let manager = manager_with_filters(None, &[]);
+        assert!(manager.matches_filters("/dev/input/event3", Some("Some Keyboard")));
+        assert!(manager.matches_filters("/dev/input/event3", None));
+    }
+
+    #[test]
+    fn matches_filters_by_exact_name() {
+        let manager = manager_with_filters(Some("Some Keyboard"), &[]);
+        assert!(manager.matches_filters("/dev/input/event3", Some("Some Keyboard")));
+        assert!(!manager.matches_filters("/dev/input/event3", Some("Other Keyboard")));
+        assert!(!manager.matches_filters("/dev/input/event3", None));
+    }
+
+    #[test]
+    fn matches_filters_by_path_takes_precedence_over_name() {
+        let manager = manager_with_filters(Some("Some Keyboard"), &["/dev/input/event3"]);
+        assert!(manager.matches_filters("/dev/input/event3", Some("Other Keyboard")));
+        assert!(!manager.matches_filters("/dev/input/event4", Some("Some Keyboard")));
+    }
+
+    fn binding_config(keys: &[&str], mode: BindingMode, match_mode: BindingMatch) -> BindingConfig {
+        BindingConfig {
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            mode,
+            match_mode,
+        }
+    }
+
+    #[test]
+    fn parse_binding_rejects_empty_keys() {
+        let config = binding_config(&[], BindingMode::Hold, BindingMatch::All);
+        assert!(parse_binding(&config).is_err());
+    }
+
+    #[test]
+    fn parse_binding_rejects_unknown_key_name() {
+        let config = binding_config(&["KEY_NOT_REAL"], BindingMode::Hold, BindingMatch::All);
+        assert!(parse_binding(&config).is_err());
+    }
+
+    #[test]
+    fn parse_binding_accepts_valid_keys() {
+        let config = binding_config(
+            &["KEY_LEFTCTRL", "KEY_CAPSLOCK"],
+            BindingMode::Toggle,
+            BindingMatch::Any,
+        );
+        let binding = parse_binding(&config).expect("valid binding");
+        assert_eq!(
+            binding.keys,
+            vec![KeyCode::KEY_LEFTCTRL, KeyCode::KEY_CAPSLOCK]
+        );
+        assert_eq!(binding.mode, BindingMode::Toggle);
+        assert_eq!(binding.match_mode, BindingMatch::Any);
+        assert!(!binding.active);
+    }
+
+    #[test]
+    fn parse_bindings_fails_if_any_binding_is_invalid() {
+        let configs = vec![
+            binding_config(&["KEY_CAPSLOCK"], BindingMode::Hold, BindingMatch::All),
+            binding_config(&[], BindingMode::Hold, BindingMatch::All),
+        ];
+        assert!(parse_bindings(&configs).is_err());
+    }
+
+    fn manager_with_bindings(bindings: Vec<Binding>) -> PushToTalkManager {
+        PushToTalkManager::new(bindings, None, Vec::new(), false, BackendKind::Pactl, None)
+    }
+
+    fn binding(keys: &[KeyCode], mode: BindingMode, match_mode: BindingMatch) -> Binding {
+        Binding {
+            keys: keys.to_vec(),
+            mode,
+            match_mode,
+            active: false,
+        }
+    }
+
+    #[test]
+    fn hold_binding_unmutes_only_while_held() {
+        let mut manager = manager_with_bindings(vec![binding(
+            &[KeyCode::KEY_CAPSLOCK],
+            BindingMode::Hold,
+            BindingMatch::All,
+        )]);
+        assert!(manager.muted);
+
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 1);
+        assert!(!manager.muted);
+
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 0);
+        assert!(manager.muted);
+    }
+
+    #[test]
+    fn any_match_binding_activates_on_either_key() {
+        let mut manager = manager_with_bindings(vec![binding(
+            &[KeyCode::KEY_LEFTCTRL, KeyCode::KEY_RIGHTCTRL],
+            BindingMode::Hold,
+            BindingMatch::Any,
+        )]);
+
+        manager.handle_key(KeyCode::KEY_RIGHTCTRL, 1);
+        assert!(!manager.muted);
+        manager.handle_key(KeyCode::KEY_RIGHTCTRL, 0);
+        assert!(manager.muted);
+    }
+
+    #[test]
+    fn all_match_binding_requires_every_key() {
+        let mut manager = manager_with_bindings(vec![binding(
+            &[KeyCode::KEY_LEFTCTRL, KeyCode::KEY_LEFTSHIFT],
+            BindingMode::Hold,
+            BindingMatch::All,
+        )]);
+
+        manager.handle_key(KeyCode::KEY_LEFTCTRL, 1);
+        assert!(manager.muted);
+        manager.handle_key(KeyCode::KEY_LEFTSHIFT, 1);
+        assert!(!manager.muted);
+    }
+
+    #[test]
+    fn toggle_binding_flips_mute_on_each_press() {
+        let mut manager = manager_with_bindings(vec![binding(
+            &[KeyCode::KEY_CAPSLOCK],
+            BindingMode::Toggle,
+            BindingMatch::All,
+        )]);
+
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 1);
+        assert!(!manager.muted);
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 0);
+        assert!(!manager.muted);
+
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 1);
+        assert!(manager.muted);
+    }
+
+    #[test]
+    fn multiple_hold_bindings_stay_unmuted_until_all_release() {
+        let mut manager = manager_with_bindings(vec![
+            binding(
+                &[KeyCode::KEY_CAPSLOCK],
+                BindingMode::Hold,
+                BindingMatch::All,
+            ),
+            binding(
+                &[KeyCode::KEY_LEFTCTRL],
+                BindingMode::Hold,
+                BindingMatch::All,
+            ),
+        ]);
+
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 1);
+        assert!(!manager.muted);
+        manager.handle_key(KeyCode::KEY_LEFTCTRL, 1);
+        assert!(!manager.muted);
+
+        // Releasing just one of the two active Hold bindings must not re-mute while the other
+        // is still held — this is the aggregate-state bug the previous per-binding overwrite had.
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 0);
+        assert!(!manager.muted);
+
+        manager.handle_key(KeyCode::KEY_LEFTCTRL, 0);
+        assert!(manager.muted);
+    }
+
+    #[test]
+    fn autorepeat_is_ignored() {
+        let mut manager = manager_with_bindings(vec![binding(
+            &[KeyCode::KEY_CAPSLOCK],
+            BindingMode::Hold,
+            BindingMatch::All,
+        )]);
+
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 1);
+        assert!(!manager.muted);
+        manager.handle_key(KeyCode::KEY_CAPSLOCK, 2);
+        assert!(!manager.muted);
     }
 }